@@ -2,13 +2,23 @@
 
 use rocket::serde::{json::Json, Deserialize, Serialize};
 use rocket::tokio::task;
-use rocket::{Build, Rocket};
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::{Build, Rocket, State};
 use rocket_cors::{AllowedOrigins, CorsOptions};
 use dotenv::dotenv;
 use std::env;
+use std::time::{Duration, Instant};
 use postgres::{Client, NoTls};
+use r2d2::{Pool, PooledConnection};
+use r2d2_postgres::PostgresConnectionManager;
+use prometheus::{Encoder, Gauge, Histogram, HistogramOpts, IntCounter, Registry, TextEncoder};
 use std::collections::HashMap;
 use std::error::Error;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, RwLock};
+use std::hash::Hasher;
+use siphasher::sip::SipHasher13;
 
 //
 // Structures for API communication
@@ -27,6 +37,19 @@ struct NewInteraction {
 struct InteractionResponse {
     status: String,
     is_anomaly: bool,
+    // Which detector(s) flagged this interaction: "fixed", "ewma",
+    // "fixed+ewma", or null when neither fired.
+    anomaly_mode: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(crate = "rocket::serde")]
+struct BatchInteractionResponse {
+    results: Vec<InteractionResponse>,
+    // Index within the submitted batch that caused the whole transaction
+    // to roll back, and why. Both are `None` on success.
+    failed_index: Option<usize>,
+    error: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -52,105 +75,480 @@ struct InteractionRecord {
 // Internal structures for anomaly detection calculations
 //
 
-#[derive(Debug)]
-struct Record {
-    source: i32,
-    target: i32,
-    rating: f32,
-    timestamp: i64,
-    anomaly: i16, // inserted as 0 when added
-}
-
-#[derive(Debug)]
-struct Edge {
-    target: i32,
-    rating: f32,
-    timestamp: i64,
-    anomaly: i16,
-}
-
-#[derive(Debug, Default)]
+// Running mean/variance for a node, updated via Welford's online algorithm
+// so neither field ever needs the full rating history to stay correct.
+#[derive(Debug, Default, Clone, Copy)]
 struct NodeStats {
-    sum: f32,
-    sum_sq: f32,
     count: u32,
+    mean: f32,
+    m2: f32,
 }
 
 impl NodeStats {
     fn update(&mut self, rating: f32) {
-        self.sum += rating;
-        self.sum_sq += rating * rating;
         self.count += 1;
+        let delta = rating - self.mean;
+        self.mean += delta / self.count as f32;
+        let delta2 = rating - self.mean;
+        self.m2 += delta * delta2;
     }
-    fn mean(&self) -> f32 {
-        self.sum / self.count as f32
+    fn variance(&self) -> f32 {
+        if self.count > 0 {
+            self.m2 / self.count as f32
+        } else {
+            0.0
+        }
     }
     fn std_dev(&self) -> f32 {
-        let mean = self.mean();
-        let variance = (self.sum_sq / self.count as f32) - mean * mean;
-        variance.sqrt()
+        self.variance().sqrt()
     }
 }
 
 const THRESHOLD_MULTIPLE: f32 = 2.0;
 const FIXED_THRESHOLD: f32 = 2.0;
 
+// Recency-sensitive per-node baseline: an exponentially weighted moving
+// mean/variance, so old behavior stops counting against a node.
+#[derive(Debug, Default, Clone, Copy)]
+struct EwmaStats {
+    initialized: bool,
+    mean: f32,
+    var: f32,
+}
+
+impl EwmaStats {
+    fn update(&mut self, rating: f32, alpha: f32) {
+        if !self.initialized {
+            self.mean = rating;
+            self.var = 0.0;
+            self.initialized = true;
+            return;
+        }
+        let diff = rating - self.mean;
+        let incr = alpha * diff;
+        self.mean += incr;
+        self.var = (1.0 - alpha) * (self.var + diff * incr);
+    }
+}
+
+// EWMA detector tuning, configurable through environment variables.
+#[derive(Debug, Clone, Copy)]
+struct DetectionConfig {
+    ewma_alpha: f32,
+    ewma_k: f32,
+}
+
+impl DetectionConfig {
+    fn from_env() -> Self {
+        let ewma_alpha = env::var("ANOMALY_EWMA_ALPHA")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(0.3);
+        let ewma_k = env::var("ANOMALY_EWMA_K")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(3.0);
+        Self { ewma_alpha, ewma_k }
+    }
+}
+
+// Opt-in pseudonymization of source/target identifiers via keyed SipHash,
+// so sensitive IDs never hit the database in the clear.
+#[derive(Debug, Clone, Copy)]
+struct PseudonymizationConfig {
+    enabled: bool,
+    salt_0: u64,
+    salt_1: u64,
+}
+
+impl PseudonymizationConfig {
+    fn from_env() -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let enabled = env::var("PSEUDONYMIZE_IDENTIFIERS")
+            .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+            .unwrap_or(false);
+
+        // Salts are only required when pseudonymization is actually on; a
+        // missing or unparseable salt must not silently fall back to a
+        // well-known key.
+        let (salt_0, salt_1) = if enabled {
+            let salt_0: u64 = env::var("PSEUDONYMIZATION_SALT_0")?.parse()?;
+            let salt_1: u64 = env::var("PSEUDONYMIZATION_SALT_1")?.parse()?;
+            (salt_0, salt_1)
+        } else {
+            (0, 0)
+        };
+
+        Ok(Self { enabled, salt_0, salt_1 })
+    }
+
+    // Derive a stable pseudonym for `id`, or pass it through unchanged when
+    // pseudonymization is disabled (the default, for existing plaintext
+    // datasets).
+    fn pseudonymize(&self, id: i32) -> i32 {
+        if !self.enabled {
+            return id;
+        }
+        let mut hasher = SipHasher13::new_with_keys(self.salt_0, self.salt_1);
+        hasher.write_i32(id);
+        hasher.finish() as i32
+    }
+}
+
+// Incrementally maintained anomaly-detection state: per-node rating stats
+// under both detectors, plus the running totals `/stats` reports.
+#[derive(Debug, Default, Clone)]
+struct DetectionState {
+    node_stats: HashMap<i32, NodeStats>,
+    ewma_stats: HashMap<i32, EwmaStats>,
+    total_interactions: u32,
+    normal_interactions: u32,
+    anomalous_interactions: u32,
+}
+
+impl DetectionState {
+    // Classify `rating` against the source node's current stats under both
+    // detectors, fold it into both endpoints' stats and the running totals,
+    // and report whether it was flagged along with which mode(s) fired.
+    fn record(
+        &mut self,
+        source: i32,
+        target: i32,
+        rating: f32,
+        config: DetectionConfig,
+    ) -> (bool, Option<&'static str>) {
+        let src_stats = self.node_stats.entry(source).or_default();
+        src_stats.update(rating);
+        let mean = src_stats.mean;
+        let std_dev = src_stats.std_dev();
+
+        let fixed_anomaly = if std_dev == 0.0 {
+            rating.abs() > FIXED_THRESHOLD
+        } else {
+            (rating - mean).abs() > (THRESHOLD_MULTIPLE * std_dev)
+        };
+
+        let src_ewma = self.ewma_stats.entry(source).or_default();
+        let had_baseline = src_ewma.initialized;
+        src_ewma.update(rating, config.ewma_alpha);
+        let ewma_anomaly =
+            had_baseline && (rating - src_ewma.mean).abs() > (config.ewma_k * src_ewma.var.sqrt());
+
+        self.node_stats.entry(target).or_default().update(rating);
+        self.ewma_stats.entry(target).or_default().update(rating, config.ewma_alpha);
+
+        let is_anomaly = fixed_anomaly || ewma_anomaly;
+        self.total_interactions += 1;
+        if is_anomaly {
+            self.anomalous_interactions += 1;
+        } else {
+            self.normal_interactions += 1;
+        }
+
+        let mode = match (fixed_anomaly, ewma_anomaly) {
+            (true, true) => Some("fixed+ewma"),
+            (true, false) => Some("fixed"),
+            (false, true) => Some("ewma"),
+            (false, false) => None,
+        };
+
+        (is_anomaly, mode)
+    }
+}
+
+type SharedDetectionState = Arc<RwLock<DetectionState>>;
+
+// Prime the cache with a single scan of the existing table, replaying rows
+// in (approximate) insertion order so the EWMA baseline, which is
+// order-sensitive, replays the same way on every restart. `ctid` breaks
+// ties within the same `timestamp` second without assuming a schema we
+// don't control has a row id column.
+fn prime_detection_state(
+    pool: &PgPool,
+    config: DetectionConfig,
+) -> Result<SharedDetectionState, Box<dyn Error + Send + Sync>> {
+    let mut conn = pool.get()?;
+    let rows = conn.query(
+        "SELECT source, target, rating FROM ratings ORDER BY timestamp, ctid",
+        &[],
+    )?;
+
+    let mut state = DetectionState::default();
+    for row in rows {
+        let source: i32 = row.get("source");
+        let target: i32 = row.get("target");
+        let rating: f32 = row.get("rating");
+        state.record(source, target, rating, config);
+    }
+
+    Ok(Arc::new(RwLock::new(state)))
+}
+
 //
-// Process a new interaction: insert and calculate anomaly status.
-// Updated error type to Box<dyn Error + Send + Sync>
+// Shared Postgres connection pool, managed by Rocket as application state.
+//
+
+type PgPool = Pool<PostgresConnectionManager<NoTls>>;
+
+//
+// Request guard that hands a handler a pooled Postgres connection, borrowed
+// from the `PgPool` in managed state. Acquiring a connection can block, so
+// it happens on the blocking thread pool rather than the async executor.
+//
+struct DbConn(PooledConnection<PostgresConnectionManager<NoTls>>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for DbConn {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let pool = req
+            .rocket()
+            .state::<PgPool>()
+            .expect("PgPool not managed")
+            .clone();
+
+        match task::spawn_blocking(move || pool.get()).await {
+            Ok(Ok(conn)) => Outcome::Success(DbConn(conn)),
+            Ok(Err(e)) => {
+                println!("Error getting pooled connection: {}", e);
+                Outcome::Error((Status::ServiceUnavailable, ()))
+            }
+            Err(e) => {
+                println!("Task join error acquiring connection: {}", e);
+                Outcome::Error((Status::ServiceUnavailable, ()))
+            }
+        }
+    }
+}
+
+impl Deref for DbConn {
+    type Target = Client;
+    fn deref(&self) -> &Client {
+        &self.0
+    }
+}
+
+impl DerefMut for DbConn {
+    fn deref_mut(&mut self) -> &mut Client {
+        &mut self.0
+    }
+}
+
 //
-fn process_interaction(new_int: NewInteraction) -> Result<bool, Box<dyn Error + Send + Sync>> {
+// Build the r2d2 pool from `DATABASE_URL`, with pool sizing and timeouts
+// configurable through environment variables loaded alongside it.
+//
+fn build_db_pool() -> Result<PgPool, Box<dyn Error + Send + Sync>> {
     let connection_string = env::var("DATABASE_URL")?;
-    let mut client = Client::connect(&connection_string, NoTls)?;
+    let config: postgres::Config = connection_string.parse()?;
+    let manager = PostgresConnectionManager::new(config, NoTls);
+
+    let max_size = env::var("DB_POOL_MAX_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(10);
+    let min_idle = env::var("DB_POOL_MIN_IDLE")
+        .ok()
+        .and_then(|v| v.parse().ok());
+    let connection_timeout = env::var("DB_POOL_CONNECTION_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or_else(|| Duration::from_secs(30));
+
+    let pool = Pool::builder()
+        .max_size(max_size)
+        .min_idle(min_idle)
+        .connection_timeout(connection_timeout)
+        .build(manager)?;
+
+    Ok(pool)
+}
+
+//
+// Prometheus metrics, registered once and held in managed state so every
+// route can record against the same registry.
+//
+struct Metrics {
+    registry: Registry,
+    interactions_total: IntCounter,
+    anomalies_total: IntCounter,
+    anomaly_ratio: Gauge,
+    process_interaction_duration: Histogram,
+}
 
-    // Insert the new interaction using the current Unix epoch time.
-    let insert_query = "INSERT INTO ratings (source, target, rating, timestamp, anomaly)
+impl Metrics {
+    fn new() -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let registry = Registry::new();
+
+        let interactions_total = IntCounter::new(
+            "interactions_total",
+            "Total number of interactions ingested",
+        )?;
+        let anomalies_total = IntCounter::new(
+            "anomalies_total",
+            "Total number of interactions flagged as anomalous",
+        )?;
+        let anomaly_ratio = Gauge::new(
+            "anomaly_ratio",
+            "Ratio of anomalous to total interactions ingested so far",
+        )?;
+        let process_interaction_duration = Histogram::with_opts(HistogramOpts::new(
+            "process_interaction_duration_seconds",
+            "Latency of process_interaction in seconds",
+        ))?;
+
+        registry.register(Box::new(interactions_total.clone()))?;
+        registry.register(Box::new(anomalies_total.clone()))?;
+        registry.register(Box::new(anomaly_ratio.clone()))?;
+        registry.register(Box::new(process_interaction_duration.clone()))?;
+
+        Ok(Self {
+            registry,
+            interactions_total,
+            anomalies_total,
+            anomaly_ratio,
+            process_interaction_duration,
+        })
+    }
+
+    // Update the ingestion counters and recompute the anomaly ratio gauge.
+    fn record_interaction(&self, is_anomaly: bool) {
+        self.interactions_total.inc();
+        if is_anomaly {
+            self.anomalies_total.inc();
+        }
+        let total = self.interactions_total.get();
+        if total > 0 {
+            self.anomaly_ratio
+                .set(self.anomalies_total.get() as f64 / total as f64);
+        }
+    }
+
+    // Seed the counters/gauge from historical totals so a scrape right
+    // after startup reflects reality instead of an empty ratio.
+    fn seed_totals(&self, total_interactions: u32, anomalous_interactions: u32) {
+        self.interactions_total.inc_by(total_interactions as u64);
+        self.anomalies_total.inc_by(anomalous_interactions as u64);
+        if total_interactions > 0 {
+            self.anomaly_ratio
+                .set(anomalous_interactions as f64 / total_interactions as f64);
+        }
+    }
+}
+
+// Insert query shared by the single and batch ingestion paths, using the
+// current Unix epoch time for the timestamp.
+const INSERT_INTERACTION_QUERY: &str = "INSERT INTO ratings (source, target, rating, timestamp, anomaly)
                         VALUES ($1, $2, $3, EXTRACT(EPOCH FROM NOW())::bigint, 0)";
-    client.execute(insert_query, &[&new_int.source, &new_int.target, &new_int.rating])?;
 
-    // Retrieve historical ratings for the source.
-    let query = "SELECT rating FROM ratings WHERE source = $1";
-    let rows = client.query(query, &[&new_int.source])?;
+// Pseudonymize `new_int`'s source/target, or pass it through unchanged, so
+// both ingestion paths derive the same stored identifiers from the same
+// config.
+fn pseudonymize_interaction(pseudonymization: PseudonymizationConfig, new_int: NewInteraction) -> NewInteraction {
+    NewInteraction {
+        source: pseudonymization.pseudonymize(new_int.source),
+        target: pseudonymization.pseudonymize(new_int.target),
+        ..new_int
+    }
+}
 
-    let mut count = 0;
-    let mut sum = 0.0_f32;
-    let mut sum_sq = 0.0_f32;
-    for row in rows {
-        let r: f32 = row.get("rating");
-        sum += r;
-        sum_sq += r * r;
-        count += 1;
+//
+// Process a new interaction: insert and calculate anomaly status.
+// Updated error type to Box<dyn Error + Send + Sync>
+//
+fn process_interaction(
+    client: &mut Client,
+    state: &SharedDetectionState,
+    config: DetectionConfig,
+    pseudonymization: PseudonymizationConfig,
+    new_int: NewInteraction,
+) -> Result<(bool, Option<&'static str>), Box<dyn Error + Send + Sync>> {
+    let new_int = pseudonymize_interaction(pseudonymization, new_int);
+
+    client.execute(INSERT_INTERACTION_QUERY, &[&new_int.source, &new_int.target, &new_int.rating])?;
+
+    // Fold the new rating into the incremental cache and classify it
+    // against the source node's running stats under both detectors.
+    let mut state = state.write().unwrap();
+    let result = state.record(new_int.source, new_int.target, new_int.rating, config);
+
+    Ok(result)
+}
+
+//
+// Insert a whole batch in a single transaction, folding each item into the
+// live detection state under one held write lock. Rolls the state back to
+// match on failure. Returns the index of the offending item on failure.
+//
+fn process_batch(
+    client: &mut Client,
+    state: &SharedDetectionState,
+    config: DetectionConfig,
+    pseudonymization: PseudonymizationConfig,
+    batch: Vec<NewInteraction>,
+) -> Result<Vec<InteractionResponse>, (Option<usize>, String)> {
+    let mut txn = client
+        .transaction()
+        .map_err(|e| (None, format!("failed to start transaction: {}", e)))?;
+
+    let mut state = state.write().unwrap();
+    let state_before_batch = state.clone();
+    let mut responses = Vec::with_capacity(batch.len());
+
+    for (index, new_int) in batch.into_iter().enumerate() {
+        let new_int = pseudonymize_interaction(pseudonymization, new_int);
+        if let Err(e) = txn.execute(INSERT_INTERACTION_QUERY, &[&new_int.source, &new_int.target, &new_int.rating]) {
+            *state = state_before_batch;
+            return Err((Some(index), format!("{}", e)));
+        }
+
+        let (is_anomaly, mode) = state.record(new_int.source, new_int.target, new_int.rating, config);
+        responses.push(InteractionResponse {
+            status: if is_anomaly { "Anomaly".into() } else { "Normal".into() },
+            is_anomaly,
+            anomaly_mode: mode.map(String::from),
+        });
     }
-    let mean = sum / count as f32;
-    let std_dev = if count > 1 {
-        let variance = (sum_sq / count as f32) - (mean * mean);
-        variance.sqrt()
-    } else {
-        0.0
-    };
 
-    // Compute anomaly based on dynamic or fixed threshold.
-    let is_anomaly = if std_dev == 0.0 {
-        new_int.rating.abs() > FIXED_THRESHOLD
-    } else {
-        (new_int.rating - mean).abs() > (THRESHOLD_MULTIPLE * std_dev)
-    };
+    if let Err(e) = txn.commit() {
+        *state = state_before_batch;
+        return Err((None, format!("failed to commit transaction: {}", e)));
+    }
 
-    Ok(is_anomaly)
+    Ok(responses)
 }
 
 //
 // API endpoint to add a new interaction.
 //
 #[post("/add_interaction", format = "json", data = "<new_int>")]
-async fn add_interaction(new_int: Json<NewInteraction>) -> Json<InteractionResponse> {
+async fn add_interaction(
+    mut conn: DbConn,
+    metrics: &State<Metrics>,
+    state: &State<SharedDetectionState>,
+    config: &State<DetectionConfig>,
+    pseudonymization: &State<PseudonymizationConfig>,
+    new_int: Json<NewInteraction>,
+) -> Json<InteractionResponse> {
     let new_int = new_int.into_inner();
-    let result = task::spawn_blocking(move || process_interaction(new_int)).await;
+    let histogram = metrics.process_interaction_duration.clone();
+    let state = state.inner().clone();
+    let config = *config.inner();
+    let pseudonymization = *pseudonymization.inner();
+    let result = task::spawn_blocking(move || {
+        let start = Instant::now();
+        let outcome = process_interaction(&mut conn, &state, config, pseudonymization, new_int);
+        histogram.observe(start.elapsed().as_secs_f64());
+        outcome
+    }).await;
     match result {
-        Ok(Ok(is_anomaly)) => {
+        Ok(Ok((is_anomaly, mode))) => {
+            metrics.record_interaction(is_anomaly);
             Json(InteractionResponse {
                 status: if is_anomaly { "Anomaly".into() } else { "Normal".into() },
                 is_anomaly,
+                anomaly_mode: mode.map(String::from),
             })
         }
         Ok(Err(e)) => {
@@ -158,6 +556,7 @@ async fn add_interaction(new_int: Json<NewInteraction>) -> Json<InteractionRespo
             Json(InteractionResponse {
                 status: format!("Error processing interaction: {}", e),
                 is_anomaly: false,
+                anomaly_mode: None,
             })
         }
         Err(e) => {
@@ -165,103 +564,92 @@ async fn add_interaction(new_int: Json<NewInteraction>) -> Json<InteractionRespo
             Json(InteractionResponse {
                 status: format!("Task join error: {}", e),
                 is_anomaly: false,
+                anomaly_mode: None,
             })
         }
     }
 }
 
 //
-// API endpoint to return current network statistics.
+// API endpoint to ingest a batch of interactions in one request/transaction.
 //
-#[get("/stats")]
-async fn get_stats() -> Json<StatsResponse> {
-    let result = task::spawn_blocking(|| -> Result<StatsResponse, Box<dyn Error + Send + Sync>> {
-        let connection_string = env::var("DATABASE_URL")?;
-        let mut client = Client::connect(&connection_string, NoTls)?;
-        let query = "SELECT source, target, rating, timestamp, anomaly FROM ratings";
-        let rows = client.query(query, &[])?;
-
-        let mut adj_map: HashMap<i32, Vec<Edge>> = HashMap::new();
-        let mut node_stats: HashMap<i32, NodeStats> = HashMap::new();
-
-        for row in rows {
-            let record = Record {
-                source: row.get("source"),
-                target: row.get("target"),
-                rating: row.get("rating"),
-                timestamp: row.get("timestamp"),
-                anomaly: row.get("anomaly"),
-            };
-            let edge = Edge {
-                target: record.target,
-                rating: record.rating,
-                timestamp: record.timestamp,
-                anomaly: record.anomaly,
-            };
-            adj_map.entry(record.source)
-                .or_insert_with(Vec::new)
-                .push(edge);
-
-            node_stats.entry(record.source).or_default().update(record.rating);
-            node_stats.entry(record.target).or_default().update(record.rating);
-        }
+#[post("/batch_interactions", format = "json", data = "<batch>")]
+async fn batch_interactions(
+    mut conn: DbConn,
+    metrics: &State<Metrics>,
+    state: &State<SharedDetectionState>,
+    config: &State<DetectionConfig>,
+    pseudonymization: &State<PseudonymizationConfig>,
+    batch: Json<Vec<NewInteraction>>,
+) -> Json<BatchInteractionResponse> {
+    let batch = batch.into_inner();
+    let state = state.inner().clone();
+    let config = *config.inner();
+    let pseudonymization = *pseudonymization.inner();
+    let result = task::spawn_blocking(move || {
+        process_batch(&mut conn, &state, config, pseudonymization, batch)
+    }).await;
 
-        let mut total_interactions = 0;
-        let mut normal_interactions = 0;
-        let mut anomalous_interactions = 0;
-        for (source, edges) in &adj_map {
-            let src_stats = match node_stats.get(source) {
-                Some(stats) => stats,
-                None => continue,
-            };
-            let src_mean = src_stats.mean();
-            let src_std = src_stats.std_dev();
-            for edge in edges {
-                total_interactions += 1;
-                let diff = (edge.rating - src_mean).abs();
-                let is_anomaly = if src_std == 0.0 {
-                    edge.rating.abs() > FIXED_THRESHOLD
-                } else {
-                    diff > (THRESHOLD_MULTIPLE * src_std)
-                };
-                if is_anomaly {
-                    anomalous_interactions += 1;
-                } else {
-                    normal_interactions += 1;
-                }
+    match result {
+        Ok(Ok(results)) => {
+            for response in &results {
+                metrics.record_interaction(response.is_anomaly);
             }
+            Json(BatchInteractionResponse {
+                results,
+                failed_index: None,
+                error: None,
+            })
+        }
+        Ok(Err((failed_index, error))) => {
+            println!("Error processing batch interactions: {}", error);
+            Json(BatchInteractionResponse {
+                results: vec![],
+                failed_index,
+                error: Some(error),
+            })
         }
+        Err(e) => {
+            println!("Task join error: {}", e);
+            Json(BatchInteractionResponse {
+                results: vec![],
+                failed_index: None,
+                error: Some(format!("Task join error: {}", e)),
+            })
+        }
+    }
+}
 
-        let ratio = if total_interactions > 0 {
-            anomalous_interactions as f32 / total_interactions as f32
-        } else {
-            0.0
-        };
+//
+// API endpoint to return current network statistics. Served entirely from
+// the incrementally maintained `DetectionState`, with no database access.
+//
+#[get("/stats")]
+fn get_stats(state: &State<SharedDetectionState>) -> Json<StatsResponse> {
+    let state = state.read().unwrap();
 
-        Ok(StatsResponse {
-            total_interactions,
-            normal_interactions,
-            anomalous_interactions,
-            anomaly_ratio: ratio,
-        })
-    }).await;
+    let ratio = if state.total_interactions > 0 {
+        state.anomalous_interactions as f32 / state.total_interactions as f32
+    } else {
+        0.0
+    };
 
-    match result {
-        Ok(Ok(stats)) => Json(stats),
-        _ => Json(StatsResponse { total_interactions: 0, normal_interactions: 0, anomalous_interactions: 0, anomaly_ratio: 0.0 }),
-    }
+    Json(StatsResponse {
+        total_interactions: state.total_interactions,
+        normal_interactions: state.normal_interactions,
+        anomalous_interactions: state.anomalous_interactions,
+        anomaly_ratio: ratio,
+    })
 }
 
 //
 // API endpoint to return all interactions as JSON.
 //
 #[get("/all_interactions")]
-async fn get_all_interactions() -> Json<Vec<InteractionRecord>> {
-    let result = task::spawn_blocking(|| -> Result<Vec<InteractionRecord>, Box<dyn Error + Send + Sync>> {
-        let connection_string = env::var("DATABASE_URL")?;
-        let mut client = Client::connect(&connection_string, NoTls)?;
+async fn get_all_interactions(mut conn: DbConn) -> Json<Vec<InteractionRecord>> {
+    let result = task::spawn_blocking(move || -> Result<Vec<InteractionRecord>, Box<dyn Error + Send + Sync>> {
         let query = "SELECT source, target, rating, timestamp, anomaly FROM ratings";
-        let rows = client.query(query, &[])?;
+        let rows = conn.query(query, &[])?;
         let mut interactions = Vec::new();
         for row in rows {
             let rec = InteractionRecord {
@@ -291,7 +679,21 @@ async fn table_page() -> Option<rocket::fs::NamedFile> {
 }
 
 //
-// Build the Rocket application with CORS enabled.
+// Expose ingestion counters, the anomaly ratio, and process_interaction
+// latency in Prometheus text exposition format for scraping.
+//
+#[get("/metrics")]
+fn metrics(metrics: &State<Metrics>) -> (ContentType, String) {
+    let metric_families = metrics.registry.gather();
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&metric_families, &mut buffer)
+        .expect("failed to encode Prometheus metrics");
+    (ContentType::Plain, String::from_utf8(buffer).expect("Prometheus metrics were not valid UTF-8"))
+}
+
+//
+// Build the Rocket application with CORS enabled and a Postgres pool managed.
 //
 fn build_rocket() -> Rocket<Build> {
     let allowed_origins = AllowedOrigins::all();
@@ -304,8 +706,25 @@ fn build_rocket() -> Rocket<Build> {
         .to_cors()
         .expect("error creating CORS fairing");
 
+    let pool = build_db_pool().expect("failed to build Postgres connection pool");
+    let app_metrics = Metrics::new().expect("failed to register Prometheus metrics");
+    let detection_config = DetectionConfig::from_env();
+    let detection_state = prime_detection_state(&pool, detection_config)
+        .expect("failed to prime detection state from ratings table");
+    {
+        let primed = detection_state.read().unwrap();
+        app_metrics.seed_totals(primed.total_interactions, primed.anomalous_interactions);
+    }
+    let pseudonymization_config = PseudonymizationConfig::from_env()
+        .expect("failed to load pseudonymization config");
+
     rocket::build()
-        .mount("/", routes![add_interaction, get_stats, get_all_interactions, table_page])
+        .manage(pool)
+        .manage(app_metrics)
+        .manage(detection_state)
+        .manage(detection_config)
+        .manage(pseudonymization_config)
+        .mount("/", routes![add_interaction, batch_interactions, get_stats, get_all_interactions, table_page, metrics])
         .attach(cors)
 }
 